@@ -7,6 +7,7 @@ pub struct PingConfig {
 	timeout: Duration,		// waiting duration for reply
 	ttl: u8,				// Time To Live
 	packet_size: usize,		// Packet size in bytes
+	json: bool,				// Emit machine-readable JSON instead of text
 }
 
 impl Default for PingConfig {
@@ -17,6 +18,7 @@ impl Default for PingConfig {
 			timeout: Duration::from_secs(2),
 			ttl: 64,
 			packet_size: 56,
+			json: false,
 		}
 	}
 }
@@ -32,6 +34,7 @@ impl PingConfig {
 	pub fn timeout(&self) -> Duration { self.timeout }
 	pub fn ttl(&self) -> u8 { self.ttl }
 	pub fn packet_size(&self) -> usize { self.packet_size }
+	pub fn json(&self) -> bool { self.json }
 
 	// Builder methods
 	pub fn with_count(mut self, count: u32) -> Self {
@@ -43,4 +46,22 @@ impl PingConfig {
         self.interval = interval;
         self
     }
+
+	pub fn with_json(mut self, json: bool) -> Self {
+		self.json = json;
+		self
+	}
+
+	pub fn with_ttl(mut self, ttl: u8) -> Self {
+		self.ttl = ttl;
+		self
+	}
+
+	pub fn with_packet_size(mut self, packet_size: usize) -> Self {
+		// The payload carries a 16-byte send timestamp (see
+		// Pinger::send_ping), so anything smaller would silently truncate
+		// it and every RTT would read back as 0ms.
+		self.packet_size = packet_size.max(16);
+		self
+	}
 }
\ No newline at end of file
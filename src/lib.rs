@@ -5,15 +5,18 @@ mod error;
 mod stats;
 mod config;
 mod pinger;
+mod multi_pinger;
+mod wire;
 
 use std::{net::IpAddr, time::Duration};
 
 use dns_lookup::lookup_host;
 // Public re-export of necessary elements
 pub use error::PingError;
-pub use stats::PingStats;
+pub use stats::{PingEvent, PingReply, PingStats};
 pub use config::PingConfig;
 pub use pinger::Pinger;
+pub use multi_pinger::MultiPinger;
 
 // Custom type Result
 pub type Result<T> = std::result::Result<T, PingError>;
@@ -22,6 +25,9 @@ pub struct PingOpts {
     pub target: IpAddr,
     pub count: Option<u32>,
     pub interval: Option<Duration>,
+    pub json: bool,
+    pub ttl: Option<u8>,
+    pub packet_size: Option<usize>,
 }
 
 pub fn parse_args(args: &[String]) -> Result<PingOpts> {
@@ -34,6 +40,9 @@ pub fn parse_args(args: &[String]) -> Result<PingOpts> {
         target: IpAddr::V4(std::net::Ipv4Addr::new(0, 0, 0, 0)),
         count: None,
         interval: None,
+        json: false,
+        ttl: None,
+        packet_size: None,
     };
 
     let mut i = 1;
@@ -54,19 +63,36 @@ pub fn parse_args(args: &[String]) -> Result<PingOpts> {
                 let secs: f64 = args[i].parse().map_err(|_| PingError::InvalidAddress("Invalid interval value".to_string()))?;
                 opts.interval = Some(Duration::from_secs_f64(secs));
             }
+            "-j" | "--json" => {
+                opts.json = true;
+            }
+            "-t" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err(PingError::InvalidAddress("Missing TTL value".to_string()));
+                }
+                opts.ttl = Some(args[i].parse().map_err(|_| PingError::InvalidAddress("Invalid TTL value".to_string()))?);
+            }
+            "-s" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err(PingError::InvalidAddress("Missing packet size value".to_string()));
+                }
+                opts.packet_size = Some(args[i].parse().map_err(|_| PingError::InvalidAddress("Invalid packet size value".to_string()))?);
+            }
             arg => {
                 // Parse target (IP or hostname)
                 match arg.parse::<IpAddr>() {
-                    Ok(ip) => match ip {
-                            IpAddr::V4(_) => opts.target = ip,
-                            IpAddr::V6(_) => return Err(PingError::InvalidAddress("IPv6 isn't supported yet".to_string())),
-                    }
+                    Ok(ip) => opts.target = ip,
                     Err(_) => {
                         match lookup_host(arg) {
                             Ok(ips) => {
-                                opts.target = ips.into_iter()
-                                    .find(|ip| ip.is_ipv4())
-                                    .ok_or_else(|| PingError::InvalidAddress("No IPv4 address found".to_string()))?;
+                                // Prefer IPv4 for parity with most resolvers, but
+                                // fall back to IPv6 if that's all the host has.
+                                let ipv4 = ips.iter().find(|ip| ip.is_ipv4()).copied();
+                                opts.target = ipv4
+                                    .or_else(|| ips.into_iter().find(|ip| ip.is_ipv6()))
+                                    .ok_or_else(|| PingError::InvalidAddress("No address found for host".to_string()))?;
                             }
                             Err(_) => return Err(PingError::InvalidAddress("Could not resolve hostname".to_string())),
                         }
@@ -98,6 +124,23 @@ mod tests {
         assert_eq!(config.interval(), Duration::from_secs(1));
     }
 
+    #[test]
+    fn test_packet_size_clamps_to_minimum() {
+        // Below 16 bytes there's no room for the send timestamp embedded in
+        // the payload (see Pinger::send_ping), so it must be clamped up.
+        let config = PingConfig::default().with_packet_size(8);
+        assert_eq!(config.packet_size(), 16);
+
+        let config = PingConfig::default().with_packet_size(0);
+        assert_eq!(config.packet_size(), 16);
+    }
+
+    #[test]
+    fn test_packet_size_above_minimum_is_unchanged() {
+        let config = PingConfig::default().with_packet_size(100);
+        assert_eq!(config.packet_size(), 100);
+    }
+
     #[test]
     fn test_parse_valid_ipv4() {
         let args = vec![
@@ -126,7 +169,8 @@ mod tests {
             String::from("::1"),
         ];
         let result = crate::parse_args(&args);
-        assert!(result.is_err());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().target, IpAddr::from_str("::1").unwrap());
     }
 
     #[test]
@@ -164,6 +208,33 @@ mod tests {
         assert_eq!(stats.get_loss_percetange(), 25.0);
     }
 
+    #[test]
+    fn test_mdev_rtt_is_zero_for_identical_samples() {
+        let mut stats = PingStats::new();
+        for _ in 0..3 {
+            stats.record_received(Duration::from_millis(100));
+        }
+        assert_eq!(stats.get_mdev_rtt(), Some(0.0));
+    }
+
+    #[test]
+    fn test_mdev_rtt_reflects_sample_spread() {
+        let mut stats = PingStats::new();
+        stats.record_received(Duration::from_millis(10));
+        stats.record_received(Duration::from_millis(20));
+        stats.record_received(Duration::from_millis(30));
+
+        // mean = 20, variance = ((10)^2 + 0^2 + 10^2) / 3, mdev = sqrt(variance)
+        let mdev = stats.get_mdev_rtt().unwrap();
+        assert!((mdev - (200.0_f64 / 3.0).sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_mdev_rtt_is_none_without_samples() {
+        let stats = PingStats::new();
+        assert_eq!(stats.get_mdev_rtt(), None);
+    }
+
     #[test]
     fn test_ping_error_handling() {
         let invalid_ip = "256.256.256.256";
@@ -217,4 +288,102 @@ mod tests {
         let result = parse_args(&args);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_parse_args_json_flag() {
+        let args = vec![
+            String::from("program"),
+            String::from("-j"),
+            String::from("127.0.0.1"),
+        ];
+        let opts = parse_args(&args).unwrap();
+        assert!(opts.json);
+    }
+
+    #[test]
+    fn test_parse_args_json_long_flag() {
+        let args = vec![
+            String::from("program"),
+            String::from("--json"),
+            String::from("127.0.0.1"),
+        ];
+        let opts = parse_args(&args).unwrap();
+        assert!(opts.json);
+    }
+
+    #[test]
+    fn test_parse_args_defaults_json_to_false() {
+        let args = vec![
+            String::from("program"),
+            String::from("127.0.0.1"),
+        ];
+        let opts = parse_args(&args).unwrap();
+        assert!(!opts.json);
+    }
+
+    #[test]
+    fn test_parse_args_ttl_flag() {
+        let args = vec![
+            String::from("program"),
+            String::from("-t"),
+            String::from("32"),
+            String::from("127.0.0.1"),
+        ];
+        let opts = parse_args(&args).unwrap();
+        assert_eq!(opts.ttl, Some(32));
+    }
+
+    #[test]
+    fn test_parse_args_ttl_missing_value() {
+        let args = vec![
+            String::from("program"),
+            String::from("-t"),
+        ];
+        let result = parse_args(&args);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_args_ttl_invalid_value() {
+        let args = vec![
+            String::from("program"),
+            String::from("-t"),
+            String::from("not-a-number"),
+        ];
+        let result = parse_args(&args);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_args_packet_size_flag() {
+        let args = vec![
+            String::from("program"),
+            String::from("-s"),
+            String::from("100"),
+            String::from("127.0.0.1"),
+        ];
+        let opts = parse_args(&args).unwrap();
+        assert_eq!(opts.packet_size, Some(100));
+    }
+
+    #[test]
+    fn test_parse_args_packet_size_missing_value() {
+        let args = vec![
+            String::from("program"),
+            String::from("-s"),
+        ];
+        let result = parse_args(&args);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_args_packet_size_invalid_value() {
+        let args = vec![
+            String::from("program"),
+            String::from("-s"),
+            String::from("not-a-number"),
+        ];
+        let result = parse_args(&args);
+        assert!(result.is_err());
+    }
 }
\ No newline at end of file
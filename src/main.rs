@@ -12,8 +12,17 @@ fn main() -> Result<(), PingError> {
     if let Some(interval) = opts.interval {
         pinger = pinger.with_interval(interval);
     }
+    if let Some(ttl) = opts.ttl {
+        pinger = pinger.with_ttl(ttl);
+    }
+    if let Some(packet_size) = opts.packet_size {
+        pinger = pinger.with_packet_size(packet_size);
+    }
+    pinger = pinger.with_json(opts.json);
 
-    println!("PING {} ({}): {} data bytes", opts.target, opts.target, 56);
+    if !opts.json {
+        println!("PING {} ({}): {} data bytes", opts.target, opts.target, opts.packet_size.map_or(56, |size| size.max(16)));
+    }
     pinger.run()?;
 
     Ok(())
@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::wire::{self, IcmpFamily, ICMPV4_ECHO_REPLY, ICMPV4_ECHO_REQUEST, ICMPV6_ECHO_REPLY, ICMPV6_ECHO_REQUEST};
+use crate::{PingConfig, PingError, PingStats};
+
+/// Matches an inbound Echo Reply back to the in-flight request that
+/// triggered it. `seq` is shared across every target in a round (it's
+/// incremented once per round, not once per target), so it alone can't
+/// tell two targets' probes apart; keying by `(target, seq)` instead
+/// disambiguates them. The ICMP identifier is checked separately in
+/// `recv_echo_reply` so replies to other processes' pings are ignored
+/// before they ever reach this map.
+type PendingKey = (IpAddr, u16);
+
+struct PendingPing {
+    sent_at: Instant,
+}
+
+/// Pings many targets concurrently on shared raw sockets (one per address
+/// family in use). A sender loop on the calling thread fans Echo Requests
+/// out at a fixed interval while a background thread per socket drains
+/// replies as they arrive. Unlike `Pinger::run`, which sends and blocks on
+/// a reply before moving on to the next probe, requests to every target can
+/// be in flight at once; replies are matched back to their target via the
+/// ICMP identifier and sequence number rather than relying on serial
+/// ordering. Each time a reply arrives or a probe times out, the updated
+/// `PingStats` snapshot for that host is pushed to the returned channel;
+/// sending a probe updates the stats without pushing a snapshot.
+pub struct MultiPinger {
+    targets: Vec<IpAddr>,
+    config: PingConfig,
+    identifier: u16,
+    running: Arc<AtomicBool>,
+    receiver_threads: Mutex<Vec<std::thread::JoinHandle<()>>>,
+}
+
+impl MultiPinger {
+    pub fn new(targets: Vec<IpAddr>) -> Self {
+        Self {
+            targets,
+            config: PingConfig::default(),
+            // Low bits of our pid double as the ICMP identifier, so replies
+            // to another process's pings don't get matched to ours.
+            identifier: std::process::id() as u16,
+            running: Arc::new(AtomicBool::new(true)),
+            receiver_threads: Mutex::new(Vec::new()),
+        }
+    }
+
+    // builder-pattern config, same as Pinger
+    pub fn with_count(mut self, count: u32) -> Self {
+        self.config = self.config.with_count(count);
+        self
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.config = self.config.with_interval(interval);
+        self
+    }
+
+    /// Starts the sender loop and one receiver thread per address family in
+    /// use, returning a channel of `(target, PingStats)` snapshots, one per
+    /// observed reply or timeout. The sender loop runs on the calling thread
+    /// and returns once `count` rounds have been sent (or never, if `count`
+    /// is `None`, until `stop()` is called from another thread).
+    pub fn run(&self) -> Result<Receiver<(IpAddr, PingStats)>, PingError> {
+        let sockets = Self::open_sockets(&self.targets, self.config.timeout())?;
+        let pending: Arc<Mutex<HashMap<PendingKey, PendingPing>>> = Arc::new(Mutex::new(HashMap::new()));
+        let stats: Arc<Mutex<HashMap<IpAddr, PingStats>>> = Arc::new(Mutex::new(HashMap::new()));
+        let (tx, rx) = mpsc::channel();
+
+        for (&family, socket) in &sockets {
+            let recv_socket = socket.try_clone()?;
+            let identifier = self.identifier;
+            let pending = Arc::clone(&pending);
+            let stats = Arc::clone(&stats);
+            let tx = tx.clone();
+            let running = Arc::clone(&self.running);
+            let handle = std::thread::spawn(move || {
+                Self::receive_loop(family, recv_socket, identifier, pending, stats, tx, running);
+            });
+            self.receiver_threads.lock().unwrap().push(handle);
+        }
+
+        let mut seq: u16 = 0;
+        while self.should_continue(seq as u32) && self.running.load(Ordering::SeqCst) {
+            for &target in &self.targets {
+                if let Some(socket) = sockets.get(&IcmpFamily::of(target)) {
+                    let sent_at = Instant::now();
+                    if Self::send_echo_request(socket, target, self.identifier, seq).is_ok() {
+                        pending.lock().unwrap().insert((target, seq), PendingPing { sent_at });
+                        stats.lock().unwrap().entry(target).or_default().record_sent();
+                    }
+                }
+            }
+            seq = seq.wrapping_add(1);
+            std::thread::sleep(self.config.interval());
+            Self::reap_timeouts(&pending, &stats, self.config.timeout(), &tx);
+        }
+
+        // Give the last round's outstanding replies a chance to land before
+        // we return, the same grace every earlier round already got from
+        // the `reap_timeouts` call above; otherwise they'd just vanish from
+        // the channel with no loss notification.
+        std::thread::sleep(self.config.timeout());
+        Self::reap_timeouts(&pending, &stats, self.config.timeout(), &tx);
+
+        Ok(rx)
+    }
+
+    /// Stops the sender loop and joins every receiver thread started by
+    /// `run`. Each receiver socket has a read timeout (see `open_sockets`),
+    /// so a thread blocked in `recv_from` still wakes up to observe
+    /// `running` going false instead of blocking forever on a quiet target.
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+        let mut threads = self.receiver_threads.lock().unwrap();
+        for handle in threads.drain(..) {
+            let _ = handle.join();
+        }
+    }
+
+    fn should_continue(&self, seq: u32) -> bool {
+        match self.config.count() {
+            Some(count) => seq < count,
+            None => true,
+        }
+    }
+
+    fn open_sockets(targets: &[IpAddr], timeout: Duration) -> Result<HashMap<IcmpFamily, Socket>, PingError> {
+        let mut sockets = HashMap::new();
+        for &target in targets {
+            let family = IcmpFamily::of(target);
+            if sockets.contains_key(&family) {
+                continue;
+            }
+            let (domain, protocol) = match family {
+                IcmpFamily::V4 => (Domain::IPV4, Protocol::ICMPV4),
+                IcmpFamily::V6 => (Domain::IPV6, Protocol::ICMPV6),
+            };
+            let socket = Socket::new(domain, Type::RAW, Some(protocol))?;
+            // Without a read timeout, a quiet target's receiver thread would
+            // block in `recv_from` forever and never observe `stop()`.
+            socket.set_read_timeout(Some(timeout))?;
+            sockets.insert(family, socket);
+        }
+        Ok(sockets)
+    }
+
+    fn send_echo_request(socket: &Socket, target: IpAddr, identifier: u16, seq: u16) -> Result<(), PingError> {
+        let family = IcmpFamily::of(target);
+
+        let mut buf = vec![0u8; 8];
+        buf[0] = match family {
+            IcmpFamily::V4 => ICMPV4_ECHO_REQUEST,
+            IcmpFamily::V6 => ICMPV6_ECHO_REQUEST,
+        };
+        buf[1] = 0; // Code
+        buf[4..6].copy_from_slice(&identifier.to_be_bytes());
+        buf[6..8].copy_from_slice(&seq.to_be_bytes());
+
+        // IPv4 checksums are ours to compute; ICMPv6 checksums cover a
+        // pseudo-header we don't have here, so we leave it zero and rely on
+        // the kernel to fill it in for raw ICMPv6 sockets.
+        if family == IcmpFamily::V4 {
+            let checksum = wire::checksum(&buf);
+            buf[2..4].copy_from_slice(&checksum.to_be_bytes());
+        }
+
+        let addr = std::net::SocketAddr::new(target, 0).into();
+        socket.send_to(&buf, &addr)?;
+        Ok(())
+    }
+
+    fn receive_loop(
+        family: IcmpFamily,
+        socket: Socket,
+        identifier: u16,
+        pending: Arc<Mutex<HashMap<PendingKey, PendingPing>>>,
+        stats: Arc<Mutex<HashMap<IpAddr, PingStats>>>,
+        tx: mpsc::Sender<(IpAddr, PingStats)>,
+        running: Arc<AtomicBool>,
+    ) {
+        while running.load(Ordering::SeqCst) {
+            let key = match Self::recv_echo_reply(&socket, family, identifier) {
+                Ok(Some(key)) => key,
+                Ok(None) => continue, // not an Echo Reply we recognize
+                Err(PingError::SocketError(e))
+                    if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) =>
+                {
+                    // Just the read timeout firing so we can check `running`.
+                    continue;
+                }
+                Err(_) => break,
+            };
+            let (target, _seq) = key;
+            let entry = pending.lock().unwrap().remove(&key);
+            if let Some(entry) = entry {
+                let mut stats = stats.lock().unwrap();
+                let host_stats = stats.entry(target).or_default();
+                host_stats.record_received(entry.sent_at.elapsed());
+                let _ = tx.send((target, host_stats.clone()));
+            }
+        }
+    }
+
+    fn recv_echo_reply(socket: &Socket, family: IcmpFamily, identifier: u16) -> Result<Option<PendingKey>, PingError> {
+        let mut buf = vec![std::mem::MaybeUninit::uninit(); 64];
+        let (len, addr) = socket.recv_from(&mut buf)?;
+        let buf = &buf[..len];
+        let buf: Vec<u8> = buf.iter().map(|x| unsafe { x.assume_init() }).collect();
+
+        // IPv4 raw sockets still hand us the IP header in front of the
+        // ICMP payload; IPv6 raw sockets deliver only the ICMPv6 payload.
+        let (icmp_offset, echo_reply_type) = match family {
+            IcmpFamily::V4 => (20, ICMPV4_ECHO_REPLY),
+            IcmpFamily::V6 => (0, ICMPV6_ECHO_REPLY),
+        };
+
+        if len < icmp_offset + 8 || buf[icmp_offset] != echo_reply_type {
+            return Ok(None);
+        }
+
+        let reply_identifier = u16::from_be_bytes([buf[icmp_offset + 4], buf[icmp_offset + 5]]);
+        if reply_identifier != identifier {
+            // Another process's ping, not ours.
+            return Ok(None);
+        }
+
+        // The key is `(target, seq)`, not `(identifier, seq)`: seq alone is
+        // shared by every target pinged in the same round, so we key by the
+        // reply's source address instead to disambiguate them.
+        let target = addr.as_socket().ok_or(PingError::InvalidResponse)?.ip();
+        let seq = u16::from_be_bytes([buf[icmp_offset + 6], buf[icmp_offset + 7]]);
+        Ok(Some((target, seq)))
+    }
+
+    fn reap_timeouts(
+        pending: &Arc<Mutex<HashMap<PendingKey, PendingPing>>>,
+        stats: &Arc<Mutex<HashMap<IpAddr, PingStats>>>,
+        timeout: Duration,
+        tx: &mpsc::Sender<(IpAddr, PingStats)>,
+    ) {
+        let mut pending = pending.lock().unwrap();
+        let expired: Vec<PendingKey> = pending
+            .iter()
+            .filter(|(_, p)| p.sent_at.elapsed() >= timeout)
+            .map(|(k, _)| *k)
+            .collect();
+        for key in expired {
+            if pending.remove(&key).is_some() {
+                // Record the loss by simply not calling record_received;
+                // packets_sent was already bumped when the probe went out.
+                let target = key.0;
+                let stats = stats.lock().unwrap();
+                if let Some(host_stats) = stats.get(&target) {
+                    let _ = tx.send((target, host_stats.clone()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_continue_is_unbounded_without_a_count() {
+        let pinger = MultiPinger::new(vec![IpAddr::from([127, 0, 0, 1])]);
+        assert!(pinger.should_continue(0));
+        assert!(pinger.should_continue(1_000));
+    }
+
+    #[test]
+    fn should_continue_stops_once_count_is_reached() {
+        let pinger = MultiPinger::new(vec![IpAddr::from([127, 0, 0, 1])]).with_count(3);
+        assert!(pinger.should_continue(0));
+        assert!(pinger.should_continue(2));
+        assert!(!pinger.should_continue(3));
+        assert!(!pinger.should_continue(4));
+    }
+
+    #[test]
+    fn reap_timeouts_reports_loss_for_expired_pings_only() {
+        let target: IpAddr = IpAddr::from([127, 0, 0, 1]);
+        let expired_key: PendingKey = (target, 0);
+        let fresh_key: PendingKey = (target, 1);
+
+        let pending = Arc::new(Mutex::new(HashMap::from([
+            (expired_key, PendingPing { sent_at: Instant::now() - Duration::from_secs(10) }),
+            (fresh_key, PendingPing { sent_at: Instant::now() }),
+        ])));
+        let mut host_stats = PingStats::new();
+        host_stats.record_sent();
+        host_stats.record_sent();
+        let stats = Arc::new(Mutex::new(HashMap::from([(target, host_stats)])));
+        let (tx, rx) = mpsc::channel();
+
+        MultiPinger::reap_timeouts(&pending, &stats, Duration::from_secs(2), &tx);
+
+        let (reported_target, _) = rx.try_recv().expect("expired ping should be reported");
+        assert_eq!(reported_target, target);
+        assert!(rx.try_recv().is_err(), "the fresh ping should not be reported");
+
+        let pending = pending.lock().unwrap();
+        assert!(!pending.contains_key(&expired_key), "expired ping should be removed");
+        assert!(pending.contains_key(&fresh_key), "fresh ping should be left pending");
+    }
+}
@@ -1,16 +1,17 @@
 use std::{net::IpAddr, time::Duration};
 use socket2::{Socket, Domain, Type, Protocol};
+use std::os::unix::io::AsRawFd;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
-use crate::{PingConfig, PingError, PingStats};
+use crate::wire::{self, IcmpFamily, IcmpMessage};
+use crate::{PingConfig, PingError, PingEvent, PingReply, PingStats};
 
 pub struct Pinger {
     target: IpAddr,
     config: PingConfig,
     stats: PingStats,
     socket: Option<Socket>, // raw socket from socket2
-    current_ping_start: Option<std::time::Instant>,
     running: Arc<AtomicBool>,
 }
 
@@ -21,7 +22,6 @@ impl Pinger {
             config: PingConfig::default(),
             stats: PingStats::new(),
             socket: None,
-            current_ping_start: None,
             running: Arc::new(AtomicBool::new(true)),
         }
     }
@@ -37,24 +37,75 @@ impl Pinger {
         self
     }
 
+    pub fn with_json(mut self, json: bool) -> Self {
+        self.config = self.config.with_json(json);
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: u8) -> Self {
+        self.config = self.config.with_ttl(ttl);
+        self
+    }
+
+    pub fn with_packet_size(mut self, packet_size: usize) -> Self {
+        self.config = self.config.with_packet_size(packet_size);
+        self
+    }
+
     // socket init
     pub fn init(&mut self) -> Result<(), PingError> {
-        let domain = if self.target.is_ipv4() {
-            Domain::IPV4
+        let (domain, protocol) = if self.target.is_ipv4() {
+            (Domain::IPV4, Protocol::ICMPV4)
         } else {
-            Domain::IPV6
+            (Domain::IPV6, Protocol::ICMPV6)
         };
 
         let socket = Socket::new(
             domain,
             Type::RAW, // Need raw socket
-            Some(Protocol::ICMPV4)
+            Some(protocol)
         )?;
 
+        if self.target.is_ipv6() {
+            // ICMPv6 checksums cover a pseudo-header (source/destination
+            // address + payload length) that we don't have at send time,
+            // so ask the kernel to fill in the checksum at offset 2 for us.
+            Self::set_icmpv6_checksum_offset(&socket)?;
+        }
+
+        if self.target.is_ipv4() {
+            socket.set_ttl(self.config.ttl() as u32)?;
+        } else {
+            socket.set_unicast_hops_v6(self.config.ttl() as u32)?;
+        }
+
+        // Without a read timeout, a single dropped reply blocks `run`
+        // forever instead of being counted as packet loss.
+        socket.set_read_timeout(Some(self.config.timeout()))?;
+
         self.socket = Some(socket);
         Ok(())
     }
 
+    #[cfg(unix)]
+    fn set_icmpv6_checksum_offset(socket: &Socket) -> Result<(), PingError> {
+        let offset: libc::c_int = 2;
+        let ret = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::IPPROTO_IPV6,
+                libc::IPV6_CHECKSUM,
+                &offset as *const _ as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if ret != 0 {
+            Err(PingError::SocketError(std::io::Error::last_os_error()))
+        } else {
+            Ok(())
+        }
+    }
+
     // main method to start ping
     pub fn run(&mut self) -> Result<(), PingError> {
         self.init()?;
@@ -67,7 +118,7 @@ impl Pinger {
         let mut seq = 0;
         while self.shoud_continue(seq) && self.running.load(Ordering::SeqCst) {
             self.send_ping(seq)?;
-            self.receive_pong()?;
+            self.receive_pong(seq)?;
             seq += 1;
             std::thread::sleep(self.config.interval());
         }
@@ -86,90 +137,266 @@ impl Pinger {
     fn send_ping(&mut self, seq: u32) -> Result<(), PingError> {
         let socket = self.socket.as_ref().ok_or(PingError::SocketNotInitialized)?;
 
-        // ICMP Header
-        let mut buf = vec![0u8; 8];
-        buf[0] = 8; // Type: Echo Request
+        // ICMP header (8 bytes) plus a payload padded out to `packet_size`,
+        // so the "bytes from ..." we print isn't a lie. The payload's first
+        // 16 bytes carry the send timestamp (nanoseconds since the Unix
+        // epoch), so RTT can be recovered from the echoed data alone rather
+        // than from per-probe state kept on `self`.
+        let packet_size = self.config.packet_size();
+        let mut buf = vec![0u8; 8 + packet_size];
+        buf[0] = if self.target.is_ipv4() { wire::ICMPV4_ECHO_REQUEST } else { wire::ICMPV6_ECHO_REQUEST };
         buf[1] = 0; // Code
         buf[4..6].copy_from_slice(&(seq as u16).to_be_bytes()); // Sequence number
 
-        // Checksum
-        let checksum = Self::calculate_checksum(&buf);
-        buf[2..4].copy_from_slice(&checksum.to_be_bytes());
+        let sent_at_nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let ts_bytes = sent_at_nanos.to_be_bytes();
+        let ts_len = ts_bytes.len().min(packet_size);
+        buf[8..8 + ts_len].copy_from_slice(&ts_bytes[..ts_len]);
 
-        let start = std::time::Instant::now();
+        // Checksum: for IPv4 we compute it ourselves over the ICMP bytes;
+        // for IPv6 the kernel fills it in (see IPV6_CHECKSUM in `init`).
+        if self.target.is_ipv4() {
+            let checksum = wire::checksum(&buf);
+            buf[2..4].copy_from_slice(&checksum.to_be_bytes());
+        }
 
         // Send
         let addr = Self::make_socket_addr(self.target);
         socket.send_to(&buf, &addr)?;
 
         self.stats.record_sent();
-        self.current_ping_start = Some(start);
         Ok(())
     }
 
-    fn receive_pong(&mut self) -> Result<(), PingError> {
-        let socket = self.socket.as_ref().ok_or(PingError::SocketNotInitialized)?;
+    /// Recovers the send timestamp embedded in an echoed payload by
+    /// `send_ping`, if the reply carried enough bytes back to hold it.
+    fn extract_send_timestamp(buf: &[u8], icmp_offset: usize) -> Option<std::time::SystemTime> {
+        let ts_offset = icmp_offset + 8;
+        let ts_bytes: [u8; 16] = buf.get(ts_offset..ts_offset + 16)?.try_into().ok()?;
+        let nanos = u128::from_be_bytes(ts_bytes);
+        Some(std::time::UNIX_EPOCH + Duration::from_nanos(nanos as u64))
+    }
 
-        let mut buf = vec![std::mem::MaybeUninit::uninit(); 64];
-        let (len, _addr) = socket.recv_from(&mut buf)?;
-        let buf = &buf[..len];
-        let buf: Vec<u8> = buf.iter().map(|x| unsafe { x.assume_init() }).collect();
+    /// Whether a parsed ICMP message is the one this probe is waiting on:
+    /// an Echo Reply for `seq`, or a Time Exceeded / Destination
+    /// Unreachable that quotes `seq` as the original datagram's sequence
+    /// number. Anything else is either unrelated traffic or a stale
+    /// message left over from an earlier, already-timed-out round.
+    fn matches_seq(message: &IcmpMessage, seq: u32) -> bool {
+        match message {
+            IcmpMessage::EchoReply { seq: reply_seq } => *reply_seq == seq as u16,
+            IcmpMessage::TimeExceeded { original_seq } => *original_seq == Some(seq as u16),
+            IcmpMessage::DestinationUnreachable { original_seq, .. } => *original_seq == Some(seq as u16),
+            IcmpMessage::Other { .. } => false,
+        }
+    }
 
-        if len >= 20 + 8 {
-            let icmp_type = buf[20];
-            if icmp_type == 0 {
-                if let Some(start) = self.current_ping_start.take() {
-                    let rtt = start.elapsed();
-                    self.stats.record_received(rtt);
-                    println!("{} bytes from {}: icmp_seq={} ttl={} time={:.3} ms",
-                        self.config.packet_size() + 8,
-                        self.target,
-                        u16::from_be_bytes([buf[24], buf[25]]),
-                        buf[8],
-                        rtt.as_secs_f64() * 1000.0,
-                    );
+    fn receive_pong(&mut self, seq: u32) -> Result<(), PingError> {
+        // A reply that arrives late (after we already printed a timeout for
+        // its round) is still sitting in the socket's receive queue ahead of
+        // the current round's reply, since delivery is FIFO. Keep reading
+        // and discarding stale/mismatched replies until either the current
+        // probe's reply shows up or the overall timeout for this probe
+        // elapses, instead of bailing out after a single non-matching read
+        // (which would permanently shift every later round one reply
+        // behind).
+        let deadline = std::time::Instant::now() + self.config.timeout();
+        loop {
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            if remaining.is_zero() {
+                self.print_timeout(seq);
+                return Ok(());
+            }
+
+            let socket = self.socket.as_ref().ok_or(PingError::SocketNotInitialized)?;
+            socket.set_read_timeout(Some(remaining))?;
+
+            // Worst-case header (20-byte IPv4 header) + ICMP header + our payload.
+            let recv_buf_size = 20 + 8 + self.config.packet_size();
+            let mut buf = vec![std::mem::MaybeUninit::uninit(); recv_buf_size];
+            let (len, _addr) = match socket.recv_from(&mut buf) {
+                Ok(result) => result,
+                Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {
+                    self.print_timeout(seq);
+                    return Ok(());
                 }
-                Ok(())
+                Err(e) => return Err(PingError::from(e)),
+            };
+            let buf = &buf[..len];
+            let buf: Vec<u8> = buf.iter().map(|x| unsafe { x.assume_init() }).collect();
+
+            // IPv4 raw sockets still hand us the (20-byte, no-options) IP
+            // header in front of the ICMP payload; IPv6 raw sockets deliver
+            // only the ICMPv6 payload itself.
+            let family = IcmpFamily::of(self.target);
+            let (icmp_offset, ttl) = if self.target.is_ipv4() {
+                (20, buf.get(8).copied())
             } else {
-                Err(PingError::InvalidResponse)
+                (0, None)
+            };
+            // The IPv6 hop limit isn't in the payload; fall back to our
+            // configured TTL since we don't read ancillary data.
+            let ttl = ttl.unwrap_or(self.config.ttl());
+
+            let message = wire::parse_icmp(&buf, icmp_offset, family);
+            match message {
+                Some(ref m) if !Self::matches_seq(m, seq) => {
+                    // A stale reply/error left over from a probe we already
+                    // timed out on; keep waiting for the current probe's
+                    // reply instead of ending its wait early.
+                    continue;
+                }
+                Some(IcmpMessage::EchoReply { seq: reply_seq }) => {
+                    let rtt = Self::extract_send_timestamp(&buf, icmp_offset)
+                        .and_then(|sent_at| std::time::SystemTime::now().duration_since(sent_at).ok())
+                        .unwrap_or_default();
+                    self.stats.record_received(rtt);
+                    if self.config.json() {
+                        let reply = PingReply {
+                            target: self.target.to_string(),
+                            icmp_seq: reply_seq,
+                            ttl,
+                            rtt_ms: rtt.as_secs_f64() * 1000.0,
+                        };
+                        if let Ok(json) = serde_json::to_string(&reply) {
+                            println!("{}", json);
+                        }
+                    } else {
+                        println!("{} bytes from {}: icmp_seq={} ttl={} time={:.3} ms",
+                            self.config.packet_size() + 8,
+                            self.target,
+                            reply_seq,
+                            ttl,
+                            rtt.as_secs_f64() * 1000.0,
+                        );
+                    }
+                    return Ok(());
+                }
+                Some(IcmpMessage::TimeExceeded { original_seq }) => {
+                    self.stats.record_time_exceeded();
+                    self.print_icmp_event(original_seq, "time_exceeded", "Time to live exceeded");
+                    return Ok(());
+                }
+                Some(IcmpMessage::DestinationUnreachable { original_seq, .. }) => {
+                    self.stats.record_destination_unreachable();
+                    self.print_icmp_event(original_seq, "destination_unreachable", "Destination Unreachable");
+                    return Ok(());
+                }
+                Some(IcmpMessage::Other { .. }) | None => {
+                    // A raw ICMP(v6) socket sees every message on the link,
+                    // not just replies to our own probes (NDP, Router
+                    // Advertisements, other processes' pings, ...). Ignore
+                    // anything that isn't one of the message types above
+                    // instead of aborting the whole run on the first one.
+                    continue;
+                }
+            }
+        }
+    }
+
+    /// In `-j`/`--json` mode there's no reply to report, so we stay silent
+    /// rather than injecting a non-JSON line into a JSON-per-line stream.
+    fn print_timeout(&self, seq: u32) {
+        if self.config.json() {
+            return;
+        }
+        println!("Request timeout for icmp_seq={}", seq);
+    }
+
+    /// Reports a Time Exceeded / Destination Unreachable notification as a
+    /// `PingEvent` in JSON mode, or as a text line otherwise, mirroring how
+    /// Echo Replies are reported in `receive_pong`.
+    fn print_icmp_event(&self, original_seq: Option<u16>, kind: &'static str, text_label: &str) {
+        if self.config.json() {
+            let event = PingEvent {
+                target: self.target.to_string(),
+                icmp_seq: original_seq,
+                kind,
+            };
+            if let Ok(json) = serde_json::to_string(&event) {
+                println!("{}", json);
             }
         } else {
-            Err(PingError::InvalidResponse)
+            println!("From {} icmp_seq={} {}",
+                self.target,
+                original_seq.map_or_else(|| "?".to_string(), |s| s.to_string()),
+                text_label,
+            );
         }
     }
 
     fn print_statistics(&self) {
+        if self.config.json() {
+            if let Ok(json) = serde_json::to_string(&self.stats.to_summary()) {
+                println!("{}", json);
+            }
+            return;
+        }
+
         println!("\n--- {} ping statistics ---", self.target);
         println!("{} packets transmitted, {} received, {}% packet loss",
             self.stats.packets_sent(),
             self.stats.packets_received(),
             self.stats.get_loss_percetange(),
         );
-        println!("rtt min/avg/max = {} ms", self.stats.format_rtt());
+        println!("rtt min/avg/max/mdev = {} ms", self.stats.format_rtt());
+        if self.stats.time_exceeded() > 0 || self.stats.destination_unreachable() > 0 {
+            println!("{} time exceeded, {} destination unreachable",
+                self.stats.time_exceeded(),
+                self.stats.destination_unreachable(),
+            );
+        }
     }
 
-    fn calculate_checksum(buf: &[u8]) -> u16 {
-        let mut sum = 0u32;
-        let len = buf.len();
-        let mut i = 0;
+    fn make_socket_addr(addr: IpAddr) -> socket2::SockAddr {
+        std::net::SocketAddr::new(addr, 0).into()
+    }
+}
 
-        while i < len - 1 {
-            sum += ((buf[i] as u32) << 8) | (buf[i + 1] as u32);
-            i += 2;
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        if len % 2 == 1 {
-            sum += (buf[len - 1] as u32) << 8;
-        }
+    #[test]
+    fn matches_seq_accepts_echo_reply_for_current_seq() {
+        assert!(Pinger::matches_seq(&IcmpMessage::EchoReply { seq: 5 }, 5));
+    }
 
-        while (sum >> 16) != 0 {
-            sum = (sum & 0xFFFF) + (sum >> 16);
-        }
+    #[test]
+    fn matches_seq_rejects_echo_reply_for_stale_seq() {
+        assert!(!Pinger::matches_seq(&IcmpMessage::EchoReply { seq: 4 }, 5));
+    }
 
-        !sum as u16
+    #[test]
+    fn matches_seq_accepts_time_exceeded_quoting_current_seq() {
+        assert!(Pinger::matches_seq(&IcmpMessage::TimeExceeded { original_seq: Some(5) }, 5));
     }
 
-    fn make_socket_addr(addr: IpAddr) -> socket2::SockAddr {
-        std::net::SocketAddr::new(addr, 0).into()
+    #[test]
+    fn matches_seq_rejects_time_exceeded_quoting_stale_seq() {
+        assert!(!Pinger::matches_seq(&IcmpMessage::TimeExceeded { original_seq: Some(4) }, 5));
+    }
+
+    #[test]
+    fn matches_seq_rejects_time_exceeded_with_unknown_original_seq() {
+        assert!(!Pinger::matches_seq(&IcmpMessage::TimeExceeded { original_seq: None }, 5));
+    }
+
+    #[test]
+    fn matches_seq_accepts_destination_unreachable_quoting_current_seq() {
+        assert!(Pinger::matches_seq(&IcmpMessage::DestinationUnreachable { code: 1, original_seq: Some(5) }, 5));
+    }
+
+    #[test]
+    fn matches_seq_rejects_destination_unreachable_quoting_stale_seq() {
+        assert!(!Pinger::matches_seq(&IcmpMessage::DestinationUnreachable { code: 1, original_seq: Some(4) }, 5));
+    }
+
+    #[test]
+    fn matches_seq_rejects_other_messages() {
+        assert!(!Pinger::matches_seq(&IcmpMessage::Other { icmp_type: 200, code: 0 }, 5));
     }
 }
\ No newline at end of file
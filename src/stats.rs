@@ -1,12 +1,51 @@
 use std::time::Duration;
 
-#[derive(Debug, Default)]
+use serde::Serialize;
+
+/// A single parsed Echo Reply, for `-j`/`--json` output.
+#[derive(Debug, Serialize)]
+pub struct PingReply {
+    pub target: String,
+    pub icmp_seq: u16,
+    pub ttl: u8,
+    pub rtt_ms: f64,
+}
+
+/// A Time Exceeded / Destination Unreachable notification, for `-j`/`--json`
+/// output. Kept separate from `PingReply` since these aren't Echo Replies at
+/// all (a router along the path, or the destination itself, is reporting it
+/// can't deliver the probe) and so don't carry an RTT.
+#[derive(Debug, Serialize)]
+pub struct PingEvent {
+    pub target: String,
+    pub icmp_seq: Option<u16>,
+    pub kind: &'static str,
+}
+
+/// Final run summary, for `-j`/`--json` output.
+#[derive(Debug, Serialize)]
+pub struct PingSummary {
+    pub packets_sent: u32,
+    pub packets_received: u32,
+    pub packet_loss_percent: f64,
+    pub min_rtt_ms: Option<f64>,
+    pub avg_rtt_ms: Option<f64>,
+    pub max_rtt_ms: Option<f64>,
+    pub mdev_rtt_ms: Option<f64>,
+    pub time_exceeded: u32,
+    pub destination_unreachable: u32,
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct PingStats {
     packets_sent: u32,
     packets_received: u32,
     min_rtt: Option<Duration>,
     max_rtt: Option<Duration>,
     total_rtt: Duration,
+    sum_sq_rtt_ms: f64,
+    time_exceeded: u32,
+    destination_unreachable: u32,
 }
 
 impl PingStats {
@@ -25,9 +64,27 @@ impl PingStats {
 
     pub fn record_received(&mut self, rtt: Duration) {
         self.packets_received += 1;
+        self.sum_sq_rtt_ms += Self::duration_as_ms(rtt).powi(2);
         self.update_rtt(rtt);
     }
 
+    pub fn time_exceeded(&self) -> u32 { self.time_exceeded }
+    pub fn destination_unreachable(&self) -> u32 { self.destination_unreachable }
+
+    /// A router along the path reported TTL/hop-limit expiry instead of the
+    /// destination replying. Tracked separately from `record_received` since
+    /// it isn't the Echo Reply we sent the probe for, and separately from
+    /// plain loss since we *did* get a response, just not from the target.
+    pub fn record_time_exceeded(&mut self) {
+        self.time_exceeded += 1;
+    }
+
+    /// A router or the destination reported it (or the requested service)
+    /// is unreachable, instead of replying to the Echo Request.
+    pub fn record_destination_unreachable(&mut self) {
+        self.destination_unreachable += 1;
+    }
+
     pub fn get_loss_percetange(&self) -> f64 {
         if self.packets_sent == 0 {
             return 0.0;
@@ -53,16 +110,40 @@ impl PingStats {
         d.as_secs_f64() * 1000.0
     }
 
+    /// Standard deviation of the RTT samples, in milliseconds (ping calls
+    /// this "mdev"). Derived from the running sum of squares rather than
+    /// stored samples, so it costs no extra memory per probe.
+    pub fn get_mdev_rtt(&self) -> Option<f64> {
+        let avg = Self::duration_as_ms(self.get_avg_rtt()?);
+        let mean_sq = self.sum_sq_rtt_ms / self.packets_received as f64;
+        Some((mean_sq - avg * avg).max(0.0).sqrt())
+    }
+
     pub fn format_rtt(&self) -> String {
-        match (self.min_rtt, self.get_avg_rtt(), self.max_rtt) {
-            (Some(min), Some(avg), Some(max)) => {
-                format!("{:.3}/{:.3}/{:.3}",
+        match (self.min_rtt, self.get_avg_rtt(), self.max_rtt, self.get_mdev_rtt()) {
+            (Some(min), Some(avg), Some(max), Some(mdev)) => {
+                format!("{:.3}/{:.3}/{:.3}/{:.3}",
                     Self::duration_as_ms(min),
                     Self::duration_as_ms(avg),
-                    Self::duration_as_ms(max)
+                    Self::duration_as_ms(max),
+                    mdev,
                 )
             },
-            _ => String::from("---/---/--- ms")
+            _ => String::from("---/---/---/--- ms")
+        }
+    }
+
+    pub fn to_summary(&self) -> PingSummary {
+        PingSummary {
+            packets_sent: self.packets_sent,
+            packets_received: self.packets_received,
+            packet_loss_percent: self.get_loss_percetange(),
+            min_rtt_ms: self.min_rtt.map(Self::duration_as_ms),
+            avg_rtt_ms: self.get_avg_rtt().map(Self::duration_as_ms),
+            max_rtt_ms: self.max_rtt.map(Self::duration_as_ms),
+            mdev_rtt_ms: self.get_mdev_rtt(),
+            time_exceeded: self.time_exceeded,
+            destination_unreachable: self.destination_unreachable,
         }
     }
 }
\ No newline at end of file
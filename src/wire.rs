@@ -0,0 +1,224 @@
+//! Minimal ICMP / ICMPv6 wire parsing, in the spirit of smoltcp's `wire`
+//! module: small, allocation-free decoding of just the fields the pinger
+//! needs, kept separate from the socket/send/receive plumbing in
+//! `pinger.rs`. This is also the seed of what a future traceroute mode
+//! (replies from routers along the path, not just the final host) would
+//! build on.
+
+use std::net::IpAddr;
+
+// Echo Request/Reply type values, shared by `Pinger` and `MultiPinger` so
+// the two send paths agree on message types. Where the sequence number sits
+// differs: `Pinger` writes it at bytes 4-5, `MultiPinger` writes its
+// identifier there and the sequence number at bytes 6-7.
+pub const ICMPV4_ECHO_REQUEST: u8 = 8;
+pub const ICMPV4_ECHO_REPLY: u8 = 0;
+const ICMPV4_DEST_UNREACHABLE: u8 = 3;
+const ICMPV4_TIME_EXCEEDED: u8 = 11;
+
+pub const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_DEST_UNREACHABLE: u8 = 1;
+const ICMPV6_TIME_EXCEEDED: u8 = 3;
+pub const ICMPV6_ECHO_REPLY: u8 = 129;
+
+/// Address family of a ping target, used to pick the right header layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IcmpFamily {
+    V4,
+    V6,
+}
+
+impl IcmpFamily {
+    pub fn of(target: IpAddr) -> Self {
+        if target.is_ipv4() { IcmpFamily::V4 } else { IcmpFamily::V6 }
+    }
+}
+
+/// A decoded ICMP (v4) or ICMPv6 message, distinguishing the Echo Reply we
+/// asked for from the error messages a router or the destination host can
+/// send back instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IcmpMessage {
+    EchoReply { seq: u16 },
+    /// TTL/hop limit expired in transit (type 11 / type 3).
+    TimeExceeded { original_seq: Option<u16> },
+    /// Destination (host, network, port, ...) unreachable (type 3 / type 1).
+    DestinationUnreachable { code: u8, original_seq: Option<u16> },
+    /// Recognized but not one we act on specially.
+    Other { icmp_type: u8, code: u8 },
+}
+
+/// Parses the ICMP message starting at `buf[icmp_offset..]`. `buf` is the
+/// whole datagram as handed back by `recv_from` (IPv4 raw sockets still
+/// include the 20-byte IP header in front of the ICMP payload; IPv6 raw
+/// sockets don't), so `icmp_offset` tells us where the ICMP header itself
+/// begins.
+pub fn parse_icmp(buf: &[u8], icmp_offset: usize, family: IcmpFamily) -> Option<IcmpMessage> {
+    if buf.len() < icmp_offset + 8 {
+        return None;
+    }
+
+    let icmp_type = buf[icmp_offset];
+    let code = buf[icmp_offset + 1];
+
+    match (family, icmp_type) {
+        (IcmpFamily::V4, ICMPV4_ECHO_REPLY) | (IcmpFamily::V6, ICMPV6_ECHO_REPLY) => {
+            let seq = u16::from_be_bytes([buf[icmp_offset + 4], buf[icmp_offset + 5]]);
+            Some(IcmpMessage::EchoReply { seq })
+        }
+        (IcmpFamily::V4, ICMPV4_TIME_EXCEEDED) | (IcmpFamily::V6, ICMPV6_TIME_EXCEEDED) => {
+            Some(IcmpMessage::TimeExceeded { original_seq: original_seq(buf, icmp_offset, family) })
+        }
+        (IcmpFamily::V4, ICMPV4_DEST_UNREACHABLE) | (IcmpFamily::V6, ICMPV6_DEST_UNREACHABLE) => {
+            Some(IcmpMessage::DestinationUnreachable {
+                code,
+                original_seq: original_seq(buf, icmp_offset, family),
+            })
+        }
+        _ => Some(IcmpMessage::Other { icmp_type, code }),
+    }
+}
+
+/// ICMP error messages quote the offending datagram: the original IP
+/// header followed by the first 8 bytes of its payload, which for an Echo
+/// Request is the whole original ICMP header. We skip past the 8-byte
+/// ICMP error header and the quoted IP header (20 bytes for IPv4, a fixed
+/// 40 for IPv6, both assumed option/extension-header-free) to read the
+/// sequence number back out of it.
+fn original_seq(buf: &[u8], icmp_offset: usize, family: IcmpFamily) -> Option<u16> {
+    let quoted_ip_header_len = match family {
+        IcmpFamily::V4 => 20,
+        IcmpFamily::V6 => 40,
+    };
+    let original_icmp_offset = icmp_offset + 8 + quoted_ip_header_len;
+    if buf.len() < original_icmp_offset + 8 {
+        return None;
+    }
+    Some(u16::from_be_bytes([buf[original_icmp_offset + 4], buf[original_icmp_offset + 5]]))
+}
+
+/// Internet checksum (RFC 1071) over raw ICMPv4 bytes, shared by `Pinger`
+/// and `MultiPinger`. ICMPv6's checksum covers a pseudo-header neither of
+/// them has at send time, so it's left zero and filled in by the kernel
+/// instead (see `IPV6_CHECKSUM` in `Pinger::init`).
+pub fn checksum(buf: &[u8]) -> u16 {
+    let mut sum = 0u32;
+    let len = buf.len();
+    let mut i = 0;
+
+    while i < len - 1 {
+        sum += ((buf[i] as u32) << 8) | (buf[i + 1] as u32);
+        i += 2;
+    }
+
+    if len % 2 == 1 {
+        sum += (buf[len - 1] as u32) << 8;
+    }
+
+    while (sum >> 16) != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+
+    !sum as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ICMPV4_ECHO_REPLY: u8 = 0;
+    const ICMPV4_TIME_EXCEEDED: u8 = 11;
+    const ICMPV4_DEST_UNREACHABLE: u8 = 3;
+    const ICMPV6_ECHO_REPLY: u8 = 129;
+    const ICMPV6_TIME_EXCEEDED: u8 = 3;
+    const ICMPV6_DEST_UNREACHABLE: u8 = 1;
+
+    fn echo_reply_buf(icmp_type: u8, seq: u16) -> Vec<u8> {
+        let mut buf = vec![0u8; 8];
+        buf[0] = icmp_type;
+        buf[4..6].copy_from_slice(&seq.to_be_bytes());
+        buf
+    }
+
+    /// Builds an ICMP error message that quotes a datagram with the given
+    /// original sequence number, padding the quoted IP header out to
+    /// `quoted_ip_header_len` bytes.
+    fn error_buf(icmp_type: u8, code: u8, quoted_ip_header_len: usize, original_seq: u16) -> Vec<u8> {
+        let mut buf = vec![0u8; 8 + quoted_ip_header_len + 8];
+        buf[0] = icmp_type;
+        buf[1] = code;
+        let original_icmp_offset = 8 + quoted_ip_header_len;
+        buf[original_icmp_offset + 4..original_icmp_offset + 6].copy_from_slice(&original_seq.to_be_bytes());
+        buf
+    }
+
+    #[test]
+    fn parses_v4_echo_reply() {
+        let buf = echo_reply_buf(ICMPV4_ECHO_REPLY, 42);
+        assert_eq!(parse_icmp(&buf, 0, IcmpFamily::V4), Some(IcmpMessage::EchoReply { seq: 42 }));
+    }
+
+    #[test]
+    fn parses_v6_echo_reply() {
+        let buf = echo_reply_buf(ICMPV6_ECHO_REPLY, 7);
+        assert_eq!(parse_icmp(&buf, 0, IcmpFamily::V6), Some(IcmpMessage::EchoReply { seq: 7 }));
+    }
+
+    #[test]
+    fn parses_v4_time_exceeded_with_original_seq() {
+        let buf = error_buf(ICMPV4_TIME_EXCEEDED, 0, 20, 5);
+        assert_eq!(parse_icmp(&buf, 0, IcmpFamily::V4), Some(IcmpMessage::TimeExceeded { original_seq: Some(5) }));
+    }
+
+    #[test]
+    fn parses_v6_time_exceeded_with_original_seq() {
+        let buf = error_buf(ICMPV6_TIME_EXCEEDED, 0, 40, 9);
+        assert_eq!(parse_icmp(&buf, 0, IcmpFamily::V6), Some(IcmpMessage::TimeExceeded { original_seq: Some(9) }));
+    }
+
+    #[test]
+    fn parses_v4_destination_unreachable_with_original_seq() {
+        let buf = error_buf(ICMPV4_DEST_UNREACHABLE, 1, 20, 3);
+        assert_eq!(
+            parse_icmp(&buf, 0, IcmpFamily::V4),
+            Some(IcmpMessage::DestinationUnreachable { code: 1, original_seq: Some(3) })
+        );
+    }
+
+    #[test]
+    fn parses_v6_destination_unreachable_with_original_seq() {
+        let buf = error_buf(ICMPV6_DEST_UNREACHABLE, 4, 40, 11);
+        assert_eq!(
+            parse_icmp(&buf, 0, IcmpFamily::V6),
+            Some(IcmpMessage::DestinationUnreachable { code: 4, original_seq: Some(11) })
+        );
+    }
+
+    #[test]
+    fn error_message_too_short_to_quote_original_seq_returns_none() {
+        // Long enough for the 8-byte ICMP error header, too short to reach
+        // the quoted original ICMP header.
+        let buf = vec![ICMPV4_TIME_EXCEEDED, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(parse_icmp(&buf, 0, IcmpFamily::V4), Some(IcmpMessage::TimeExceeded { original_seq: None }));
+    }
+
+    #[test]
+    fn buffer_shorter_than_icmp_header_returns_none() {
+        let buf = vec![ICMPV4_ECHO_REPLY, 0, 0, 0, 0, 0, 0];
+        assert_eq!(parse_icmp(&buf, 0, IcmpFamily::V4), None);
+    }
+
+    #[test]
+    fn unrecognized_type_is_reported_as_other() {
+        let buf = echo_reply_buf(200, 0);
+        assert_eq!(parse_icmp(&buf, 0, IcmpFamily::V4), Some(IcmpMessage::Other { icmp_type: 200, code: 0 }));
+    }
+
+    #[test]
+    fn respects_nonzero_icmp_offset() {
+        let mut buf = vec![0u8; 20];
+        let reply = echo_reply_buf(ICMPV4_ECHO_REPLY, 3);
+        buf[20 - 8..].copy_from_slice(&reply);
+        assert_eq!(parse_icmp(&buf, 12, IcmpFamily::V4), Some(IcmpMessage::EchoReply { seq: 3 }));
+    }
+}